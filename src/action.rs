@@ -1,5 +1,7 @@
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io;
+use std::path::{Path, PathBuf};
 
 use crate::file::{FsItem, FsItemType};
 use core::fmt;
@@ -61,3 +63,264 @@ impl<'a> fmt::Display for Action<'a> {
         }
     }
 }
+
+#[derive(Debug)]
+pub enum ApplyError {
+    Collision(String),
+    DuplicateDestination(String),
+    Io(io::Error),
+}
+
+impl From<io::Error> for ApplyError {
+    fn from(error: io::Error) -> Self {
+        ApplyError::Io(error)
+    }
+}
+
+impl fmt::Display for ApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApplyError::Collision(path) => {
+                write!(f, "Destination \"{}\" already exists", path)
+            }
+            ApplyError::DuplicateDestination(path) => {
+                write!(f, "Multiple actions rename to \"{}\"", path)
+            }
+            ApplyError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// What an action destined for a single fs-level move should do, once it is
+/// this move's turn to run.
+enum Step<'a, 'b> {
+    /// Run the action directly; its destination is already free.
+    Run(&'b Action<'a>),
+    /// Move the action's source out of the way into a fresh temporary name,
+    /// because its destination is still occupied by another action in this
+    /// batch (this is how rename cycles, e.g. a swap, get broken).
+    Stage(&'b Action<'a>, PathBuf),
+    /// Move a previously staged action from its temporary name into its real
+    /// destination, now that the destination has been freed.
+    Finalize(&'b Action<'a>, PathBuf),
+}
+
+impl<'a, 'b> Step<'a, 'b> {
+    fn run(&self) -> Result<(), ApplyError> {
+        match self {
+            Step::Run(action) => action.apply().map_err(ApplyError::from),
+            Step::Stage(action, temp) => {
+                fs::rename(&action.item.name, temp).map_err(ApplyError::from)
+            }
+            Step::Finalize(action, temp) => match action.action_type() {
+                ActionType::Rename(dest) => fs::rename(temp, dest).map_err(ApplyError::from),
+                ActionType::Delete => unreachable!("delete actions are never staged"),
+            },
+        }
+    }
+}
+
+/// Reserve a unique, unused path in the same directory as `source`, suitable
+/// as a temporary stash for a rename that is part of a cycle.
+fn fresh_temp_path(source: &str) -> io::Result<PathBuf> {
+    let dir = Path::new(source)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty());
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(".renamer-tmp-");
+    // A bare file name lives in the current directory.
+    let temp = builder.tempfile_in(dir.unwrap_or_else(|| Path::new(".")))?;
+    let temp_path = temp.into_temp_path();
+    let path = temp_path.to_path_buf();
+    // The file itself is just a placeholder to reserve a unique name; remove
+    // it so the upcoming `fs::rename` can take its place.
+    temp_path.close()?;
+    Ok(path)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum VisitState {
+    Unvisited,
+    InProgress,
+    Done,
+}
+
+/// Order `actions` so that every rename runs only once its destination is
+/// free: chains (e.g. shifting `1`→`2`→`3`) run in dependency order, and
+/// cycles (e.g. swapping `a` and `b`) are broken by staging one member of the
+/// cycle through a temporary name.
+fn plan<'a, 'b>(actions: &'b [Action<'a>]) -> Result<Vec<Step<'a, 'b>>, ApplyError> {
+    let source_index: HashMap<&str, usize> = actions
+        .iter()
+        .enumerate()
+        .map(|(i, action)| (action.item.name.as_str(), i))
+        .collect();
+
+    // `next[i]` is the action that must run before action `i`, because `i`
+    // wants to write to a path that action currently occupies.
+    let next: Vec<Option<usize>> = actions
+        .iter()
+        .map(|action| match action.action_type() {
+            ActionType::Rename(dest) => source_index.get(dest.as_str()).copied(),
+            ActionType::Delete => None,
+        })
+        .collect();
+
+    let mut seen_destinations: HashSet<&str> = HashSet::new();
+    for action in actions.iter() {
+        if let ActionType::Rename(dest) = action.action_type() {
+            if !seen_destinations.insert(dest.as_str()) {
+                return Err(ApplyError::DuplicateDestination(dest.clone()));
+            }
+        }
+    }
+
+    for action in actions.iter() {
+        if let ActionType::Rename(dest) = action.action_type() {
+            let freed_by_batch = source_index.contains_key(dest.as_str());
+            if !freed_by_batch && !should_rename(&action.item.name, dest) {
+                return Err(ApplyError::Collision(dest.clone()));
+            }
+        }
+    }
+
+    let mut state = vec![VisitState::Unvisited; actions.len()];
+    let mut staged_temp: Vec<Option<PathBuf>> = vec![None; actions.len()];
+    let mut steps = Vec::with_capacity(actions.len());
+
+    fn visit<'a, 'b>(
+        i: usize,
+        actions: &'b [Action<'a>],
+        next: &[Option<usize>],
+        state: &mut [VisitState],
+        staged_temp: &mut [Option<PathBuf>],
+        steps: &mut Vec<Step<'a, 'b>>,
+    ) -> io::Result<()> {
+        match state[i] {
+            VisitState::Done => return Ok(()),
+            VisitState::InProgress => {
+                // We looped back to `i`: it is part of a cycle. Break the
+                // cycle here by staging `i` out of the way immediately, so
+                // whoever is waiting on `i`'s source being freed can proceed.
+                let temp = fresh_temp_path(&actions[i].item.name)?;
+                steps.push(Step::Stage(&actions[i], temp.clone()));
+                staged_temp[i] = Some(temp);
+                return Ok(());
+            }
+            VisitState::Unvisited => {}
+        }
+
+        state[i] = VisitState::InProgress;
+        if let Some(j) = next[i] {
+            visit(j, actions, next, state, staged_temp, steps)?;
+        }
+        match staged_temp[i].take() {
+            Some(temp) => steps.push(Step::Finalize(&actions[i], temp)),
+            None => steps.push(Step::Run(&actions[i])),
+        }
+        state[i] = VisitState::Done;
+        Ok(())
+    }
+
+    for i in 0..actions.len() {
+        visit(i, actions, &next, &mut state, &mut staged_temp, &mut steps)?;
+    }
+
+    Ok(steps)
+}
+
+/// Apply a full batch of actions as a single ordered plan: renames and
+/// deletes are ordered so that destinations are always free when an action
+/// runs, shifts run in dependency order and cycles like swaps go through a
+/// temporary name instead of being silently skipped. Duplicate destinations
+/// and collisions with existing files are rejected before any step runs; if
+/// a step fails partway, the steps already run are not undone.
+pub fn apply_all<'a>(actions: &[Action<'a>]) -> Result<(), ApplyError> {
+    let steps = plan(actions)?;
+    for step in &steps {
+        step.run()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(path: PathBuf) -> FsItem {
+        FsItem {
+            item_type: FsItemType::File,
+            name: path.to_str().unwrap().to_string(),
+        }
+    }
+
+    #[test]
+    fn swap_cycle_exchanges_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        fs::write(&a, "A").unwrap();
+        fs::write(&b, "B").unwrap();
+
+        let a_item = item(a.clone());
+        let b_item = item(b.clone());
+        let actions = vec![
+            Action::new(ActionType::Rename(b.to_str().unwrap().to_string()), &a_item),
+            Action::new(ActionType::Rename(a.to_str().unwrap().to_string()), &b_item),
+        ];
+
+        apply_all(&actions).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "B");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "A");
+    }
+
+    #[test]
+    fn shift_chain_moves_each_file_one_step() {
+        let dir = tempfile::tempdir().unwrap();
+        let one = dir.path().join("1");
+        let two = dir.path().join("2");
+        let three = dir.path().join("3");
+        fs::write(&one, "one").unwrap();
+        fs::write(&two, "two").unwrap();
+
+        let one_item = item(one.clone());
+        let two_item = item(two.clone());
+        let actions = vec![
+            Action::new(
+                ActionType::Rename(two.to_str().unwrap().to_string()),
+                &one_item,
+            ),
+            Action::new(
+                ActionType::Rename(three.to_str().unwrap().to_string()),
+                &two_item,
+            ),
+        ];
+
+        apply_all(&actions).unwrap();
+        assert!(!one.exists());
+        assert_eq!(fs::read_to_string(&two).unwrap(), "one");
+        assert_eq!(fs::read_to_string(&three).unwrap(), "two");
+    }
+
+    #[test]
+    fn duplicate_destination_is_rejected_before_anything_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        let c = dir.path().join("c");
+        fs::write(&a, "A").unwrap();
+        fs::write(&b, "B").unwrap();
+
+        let a_item = item(a.clone());
+        let b_item = item(b.clone());
+        let actions = vec![
+            Action::new(ActionType::Rename(c.to_str().unwrap().to_string()), &a_item),
+            Action::new(ActionType::Rename(c.to_str().unwrap().to_string()), &b_item),
+        ];
+
+        let err = apply_all(&actions).unwrap_err();
+        assert!(matches!(err, ApplyError::DuplicateDestination(_)));
+        assert!(a.exists());
+        assert!(b.exists());
+    }
+}
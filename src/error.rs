@@ -0,0 +1,78 @@
+use core::fmt;
+use std::io;
+
+use crate::action::ApplyError;
+use crate::file::ReadFileError;
+
+/// All the ways `renamer` can fail to do its job, unified so `main` can
+/// report a single diagnostic line and exit with a meaningful status
+/// instead of panicking.
+#[derive(Debug)]
+pub enum Error {
+    Config(confy::ConfyError),
+    Pattern(regex::Error),
+    Editor { editor: String, source: io::Error },
+    ReadFile(ReadFileError),
+    Apply(ApplyError),
+    Io(io::Error),
+}
+
+impl Error {
+    /// The process exit code this error should be reported with: `2` for
+    /// usage/configuration problems the user needs to fix before rerunning,
+    /// `1` for everything else (I/O and editor failures at runtime).
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Config(_) | Error::Pattern(_) => 2,
+            Error::ReadFile(ReadFileError::Parse(_)) => 2,
+            Error::ReadFile(ReadFileError::Io(_)) => 1,
+            Error::Editor { .. } | Error::Apply(_) | Error::Io(_) => 1,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Config(err) => write!(f, "failed to load config: {}", err),
+            Error::Pattern(err) => write!(f, "invalid pattern: {}", err),
+            Error::Editor { editor, source } => {
+                write!(f, "failed to start editor \"{}\": {}", editor, source)
+            }
+            Error::ReadFile(ReadFileError::Io(err)) => write!(f, "{}", err),
+            Error::ReadFile(ReadFileError::Parse(msg)) => write!(f, "{}", msg),
+            Error::Apply(err) => write!(f, "failed to apply actions: {}", err),
+            Error::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<confy::ConfyError> for Error {
+    fn from(error: confy::ConfyError) -> Self {
+        Error::Config(error)
+    }
+}
+
+impl From<regex::Error> for Error {
+    fn from(error: regex::Error) -> Self {
+        Error::Pattern(error)
+    }
+}
+
+impl From<ReadFileError> for Error {
+    fn from(error: ReadFileError) -> Self {
+        Error::ReadFile(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<ApplyError> for Error {
+    fn from(error: ApplyError) -> Self {
+        Error::Apply(error)
+    }
+}
@@ -1,33 +1,173 @@
 use io::Stdin;
+use std::fs;
 use std::io;
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
 use regex::Regex;
+use structopt::clap::{ArgMatches, Shell};
 use structopt::StructOpt;
 
 use crate::action::Action;
+use crate::error::Error;
 use crate::file::{FsItem, FsItemType, ReadFileError};
 use std::process::Command;
 use walkdir::{DirEntry, WalkDir};
 
 mod action;
+mod error;
 mod file;
 
 /// Search for a pattern in a file and display the lines that contain it.
 #[derive(StructOpt, Debug)]
 struct Cli {
-    /// The pattern to look for
-    #[structopt(parse(from_os_str), default_value = ".*")]
-    pattern: PathBuf,
+    #[structopt(flatten)]
+    rename: RenameArgs,
+
+    #[structopt(subcommand)]
+    cmd: Option<SubCommand>,
+}
+
+#[derive(StructOpt, Debug)]
+struct RenameArgs {
+    /// The pattern to look for. Defaults to matching everything: `.*` in
+    /// regex mode, `**` in `--glob` mode
+    #[structopt(parse(from_os_str))]
+    pattern: Option<PathBuf>,
 
     #[structopt(long)]
     include_dirs: bool,
 
     #[structopt(short, long)]
     recursive: bool,
+
+    /// Interpret `pattern` as a glob (e.g. `**/*.rs`) instead of a regex
+    #[structopt(short, long)]
+    glob: bool,
+
+    /// Additionally include paths matching this pattern (repeatable, may be
+    /// combined with --exclude; the last matching rule on the command line
+    /// wins for a given path)
+    #[structopt(long)]
+    include: Vec<String>,
+
+    /// Exclude paths matching this pattern (repeatable, see --include)
+    #[structopt(long)]
+    exclude: Vec<String>,
+
+    /// Read the rename mapping from this file instead of spawning an editor
+    /// (use `-` to read from stdin); enables non-interactive, scripted use
+    #[structopt(long, parse(from_os_str))]
+    from: Option<PathBuf>,
+
+    /// Skip the confirmation prompt (only takes effect together with --from)
+    #[structopt(short = "y", long)]
+    yes: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MatchKind {
+    Include,
+    Exclude,
+}
+
+/// Recover the command-line order of `--include`/`--exclude` occurrences,
+/// since they are parsed into separate `Vec`s that each forget how they were
+/// interleaved with one another.
+fn ordered_filters(matches: &ArgMatches) -> Vec<(MatchKind, String)> {
+    let mut rules: Vec<(usize, MatchKind, String)> = Vec::new();
+    if let (Some(indices), Some(values)) =
+        (matches.indices_of("include"), matches.values_of("include"))
+    {
+        rules.extend(
+            indices
+                .zip(values)
+                .map(|(i, v)| (i, MatchKind::Include, v.to_string())),
+        );
+    }
+    if let (Some(indices), Some(values)) =
+        (matches.indices_of("exclude"), matches.values_of("exclude"))
+    {
+        rules.extend(
+            indices
+                .zip(values)
+                .map(|(i, v)| (i, MatchKind::Exclude, v.to_string())),
+        );
+    }
+    rules.sort_by_key(|(index, _, _)| *index);
+    rules
+        .into_iter()
+        .map(|(_, kind, pattern)| (kind, pattern))
+        .collect()
+}
+
+#[derive(StructOpt, Debug)]
+enum SubCommand {
+    /// Generate shell completions, written to stdout
+    Completions {
+        /// The shell to generate completions for
+        #[structopt(possible_values = &Shell::variants())]
+        shell: Shell,
+    },
+    /// Generate a man page, written to stdout
+    Man,
+}
+
+/// Escape a single regex metacharacter, if `c` is one.
+fn push_escaped(out: &mut String, c: char) {
+    if "\\.+*?()|[]{}^$".contains(c) {
+        out.push('\\');
+    }
+    out.push(c);
+}
+
+/// Translate a glob pattern into an equivalent anchored regex.
+///
+/// Literal characters (including `.`) are escaped first so they can't be
+/// reinterpreted as regex metacharacters; glob tokens are then substituted in
+/// order: `**/` becomes `(?:.*/)?` (any number of intervening directories,
+/// including none), a remaining `**` becomes `.*`, a lone `*` becomes
+/// `[^/]*` (never crosses a path separator) and `?` becomes `[^/]`.
+fn glob_to_regex(pattern: &str) -> String {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut out = String::with_capacity(chars.len() + 2);
+    out.push('^');
+
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' => {
+                let mut j = i;
+                while j < chars.len() && chars[j] == '*' {
+                    j += 1;
+                }
+                let star_count = j - i;
+                if star_count >= 2 && j < chars.len() && chars[j] == '/' {
+                    out.push_str("(?:.*/)?");
+                    i = j + 1;
+                } else if star_count >= 2 {
+                    out.push_str(".*");
+                    i = j;
+                } else {
+                    out.push_str("[^/]*");
+                    i = j;
+                }
+            }
+            '?' => {
+                out.push_str("[^/]");
+                i += 1;
+            }
+            c => {
+                push_escaped(&mut out, c);
+                i += 1;
+            }
+        }
+    }
+
+    out.push('$');
+    out
 }
 
 fn is_not_hidden(entry: &DirEntry) -> bool {
@@ -38,9 +178,21 @@ fn is_not_hidden(entry: &DirEntry) -> bool {
         .unwrap_or(false)
 }
 
+/// Decide whether `path` should be kept, applying `rules` in order and
+/// letting the last matching rule win; paths no rule matches fall back to
+/// `default_regex`, the positional pattern's own match result.
+fn is_included(path: &str, default_regex: &Regex, rules: &[(MatchKind, Regex)]) -> bool {
+    rules
+        .iter()
+        .rev()
+        .find_map(|(kind, regex)| regex.is_match(path).then(|| *kind == MatchKind::Include))
+        .unwrap_or_else(|| default_regex.is_match(path))
+}
+
 fn get_items_in_dir(
     directory: &str,
     regex: &Regex,
+    rules: &[(MatchKind, Regex)],
     recursive: bool,
     include_dirs: bool,
 ) -> Vec<FsItem> {
@@ -61,7 +213,7 @@ fn get_items_in_dir(
                 let str = file_name.to_str().unwrap();
                 let str = str.strip_prefix(directory).unwrap_or(str);
                 let str = str.trim_start_matches("/").trim_start_matches("\\");
-                if regex.is_match(str) {
+                if is_included(str, regex, rules) {
                     let item_type = if is_dir {
                         FsItemType::Directory
                     } else {
@@ -90,10 +242,14 @@ enum InputResult {
     Edit,
 }
 
-fn read_user_input(stdin: &Stdin, buffer: &mut String) -> String {
+/// Read one line of user input, lower-cased and trimmed. Returns `None` on
+/// EOF (e.g. stdin is closed or not a terminal) instead of looping forever.
+fn read_user_input(stdin: &Stdin, buffer: &mut String) -> Option<String> {
     buffer.clear();
-    stdin.read_line(buffer).unwrap();
-    return buffer.trim().to_lowercase();
+    if stdin.read_line(buffer).unwrap() == 0 {
+        return None;
+    }
+    Some(buffer.trim().to_lowercase())
 }
 
 fn read_confirmation_user_input(stdin: &Stdin, buffer: &mut String) -> InputResult {
@@ -102,10 +258,10 @@ fn read_confirmation_user_input(stdin: &Stdin, buffer: &mut String) -> InputResu
         write!(out, "Do you want to continue? (y/n/e) ").unwrap();
         out.flush().unwrap();
 
-        let res = match read_user_input(stdin, buffer).as_str() {
-            "n" => InputResult::No,
-            "y" => InputResult::Yes,
-            "e" => InputResult::Edit,
+        let res = match read_user_input(stdin, buffer).as_deref() {
+            Some("n") | None => InputResult::No,
+            Some("y") => InputResult::Yes,
+            Some("e") => InputResult::Edit,
             _ => continue,
         };
         return res;
@@ -123,30 +279,31 @@ fn read_error_confirmation_user_input(stdin: &Stdin, buffer: &mut String) -> Inp
         write!(out, "Do you want to retry editing? (y/n) ").unwrap();
         out.flush().unwrap();
 
-        return match read_user_input(stdin, buffer).as_str() {
-            "n" => InputErrorResult::No,
-            "y" => InputErrorResult::Yes,
+        return match read_user_input(stdin, buffer).as_deref() {
+            Some("n") | None => InputErrorResult::No,
+            Some("y") => InputErrorResult::Yes,
             _ => continue,
         };
     }
 }
 
-fn run_editor(editor_cmd: &mut Command, editor: &str) {
-    match editor_cmd.spawn() {
-        Ok(mut s) => {
-            s.wait().unwrap();
-        }
-        Err(err) => {
-            panic!("Failed to start editor \"{}\": {:?}", editor, err)
-        }
-    }
+fn run_editor(editor_cmd: &mut Command, editor: &str) -> Result<(), Error> {
+    let mut s = editor_cmd.spawn().map_err(|source| Error::Editor {
+        editor: editor.to_string(),
+        source,
+    })?;
+    s.wait().map_err(|source| Error::Editor {
+        editor: editor.to_string(),
+        source,
+    })?;
+    Ok(())
 }
 
 fn run_edit_process<'a>(
     editor: &str,
     outfile: &mut file::FilesFile,
     files: &'a [FsItem],
-) -> Option<Vec<Action<'a>>> {
+) -> Result<Option<Vec<Action<'a>>>, Error> {
     let stdin = io::stdin();
 
     let mut editor_cmd = Command::new(editor);
@@ -154,12 +311,12 @@ fn run_edit_process<'a>(
 
     let mut input = String::new();
     loop {
-        run_editor(&mut editor_cmd, editor);
+        run_editor(&mut editor_cmd, editor)?;
         match outfile.read(files) {
             Ok(actions) => {
                 if actions.is_empty() {
                     println!("Nothing to do");
-                    return Some(Vec::new());
+                    return Ok(Some(Vec::new()));
                 } else {
                     println!("=========Actions=========");
                     for x in actions.iter() {
@@ -170,20 +327,20 @@ fn run_edit_process<'a>(
 
                 let result = read_confirmation_user_input(&stdin, &mut input);
                 if result != InputResult::Edit {
-                    return if result == InputResult::Yes {
+                    return Ok(if result == InputResult::Yes {
                         Some(actions)
                     } else {
                         None
-                    };
+                    });
                 }
             }
             Err(err) => match err {
-                ReadFileError::Io(_) => panic!("{err:?}"),
+                ReadFileError::Io(_) => return Err(err.into()),
                 ReadFileError::Parse(str) => {
                     println!("Failed to parse file: {}", str);
                     match read_error_confirmation_user_input(&stdin, &mut input) {
                         InputErrorResult::Yes => (),
-                        InputErrorResult::No => return None,
+                        InputErrorResult::No => return Ok(None),
                     }
                 }
             },
@@ -191,6 +348,64 @@ fn run_edit_process<'a>(
     }
 }
 
+fn read_yes_no_confirmation(stdin: &Stdin, buffer: &mut String) -> bool {
+    let mut out = io::stdout();
+    loop {
+        write!(out, "Do you want to continue? (y/n) ").unwrap();
+        out.flush().unwrap();
+
+        match read_user_input(stdin, buffer).as_deref() {
+            Some("n") | None => return false,
+            Some("y") => return true,
+            _ => continue,
+        }
+    }
+}
+
+/// Open the rename mapping at `path`, or stdin if `path` is `-`.
+fn open_mapping(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    if path == Path::new("-") {
+        Ok(Box::new(BufReader::new(io::stdin())))
+    } else {
+        Ok(Box::new(BufReader::new(fs::File::open(path)?)))
+    }
+}
+
+/// Non-interactive counterpart to `run_edit_process`: read the rename
+/// mapping from `path` (or stdin) instead of spawning an editor, and skip
+/// straight to applying it, prompting for confirmation unless `yes` is set.
+fn run_batch<'a>(
+    path: &Path,
+    files: &'a [FsItem],
+    yes: bool,
+) -> Result<Option<Vec<Action<'a>>>, Error> {
+    let reader = open_mapping(path)?;
+    let actions = file::read_file_names(reader.lines(), files)?;
+
+    if actions.is_empty() {
+        println!("Nothing to do");
+        return Ok(Some(actions));
+    }
+
+    println!("=========Actions=========");
+    for x in actions.iter() {
+        println!("{}", &x)
+    }
+    println!("=========================");
+
+    if yes {
+        return Ok(Some(actions));
+    }
+
+    let stdin = io::stdin();
+    let mut input = String::new();
+    Ok(if read_yes_no_confirmation(&stdin, &mut input) {
+        Some(actions)
+    } else {
+        None
+    })
+}
+
 #[derive(Serialize, Deserialize)]
 struct Config {
     editor: String,
@@ -204,44 +419,126 @@ impl Default for Config {
     }
 }
 
-fn main() {
-    let Cli {
+/// Write shell completions for `renamer` to stdout.
+fn print_completions(shell: Shell) -> Result<(), Error> {
+    Cli::clap().gen_completions_to("renamer", shell, &mut io::stdout());
+    Ok(())
+}
+
+/// Render a man page for `renamer` to stdout.
+fn print_man() -> Result<(), Error> {
+    let app = Cli::clap();
+    let man = clap_mangen::Man::new(app);
+    man.render(&mut io::stdout())?;
+    Ok(())
+}
+
+fn run() -> Result<(), Error> {
+    let matches = Cli::clap().get_matches();
+    let cli: Cli = Cli::from_clap(&matches);
+
+    match cli.cmd {
+        Some(SubCommand::Completions { shell }) => return print_completions(shell),
+        Some(SubCommand::Man) => return print_man(),
+        None => {}
+    }
+
+    let RenameArgs {
         pattern,
         include_dirs,
         recursive,
-    }: Cli = Cli::from_args();
-    let config: Config = confy::load("renamer", None).unwrap();
+        glob,
+        from,
+        yes,
+        ..
+    } = cli.rename;
+    let config: Config = confy::load("renamer", None)?;
+
+    let to_regex = |pattern: &str| -> Result<Regex, Error> {
+        let pattern = if glob {
+            glob_to_regex(pattern)
+        } else {
+            pattern.to_string()
+        };
+        Ok(Regex::new(&pattern)?)
+    };
 
-    let regex = Regex::new(pattern.to_str().unwrap()).unwrap();
+    let pattern = match &pattern {
+        Some(pattern) => pattern.to_str().unwrap(),
+        // An empty `pattern` means "match everything", but that means
+        // different things in each mode: `.*` as a regex, `**` as a glob.
+        None if glob => "**",
+        None => ".*",
+    };
+    let regex = to_regex(pattern)?;
+    let rules = ordered_filters(&matches)
+        .into_iter()
+        .map(|(kind, pattern)| Ok((kind, to_regex(&pattern)?)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let current_dir = std::env::current_dir()?;
     let files = get_items_in_dir(
-        std::env::current_dir().unwrap().to_str().unwrap(),
+        current_dir.to_str().unwrap(),
         &regex,
+        &rules,
         recursive,
         include_dirs,
     );
 
-    let mut file = file::FilesFile::write_new(
-        tempfile::Builder::new()
-            .prefix("renamer")
-            .suffix(".ini")
-            .tempfile()
-            .unwrap(),
-        &files,
-    )
-        .unwrap();
-
-    if let Some(vec) = run_edit_process(config.editor.as_str(), &mut file, &files) {
-        for action in vec.iter() {
-            if let Err(k) = action.apply() {
-                eprintln!(
-                    "Failed to apply action for file \"{}\": {}",
-                    action.target().name,
-                    k
-                )
-            }
-        }
+    let result = if let Some(from) = from {
+        run_batch(&from, &files, yes)?
+    } else {
+        let mut file = file::FilesFile::write_new(
+            tempfile::Builder::new()
+                .prefix("renamer")
+                .suffix(".ini")
+                .tempfile()?,
+            &files,
+        )?;
+        run_edit_process(config.editor.as_str(), &mut file, &files)?
+    };
+
+    if let Some(vec) = result {
+        action::apply_all(&vec)?;
         println!("Applied actions")
     } else {
         println!("Aborted")
     }
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("renamer: error: {}", err);
+        std::process::exit(err.exit_code());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_literal_regex_metacharacters() {
+        assert_eq!(glob_to_regex("a.b+c"), r"^a\.b\+c$");
+    }
+
+    #[test]
+    fn double_star_slash_matches_any_number_of_directories() {
+        assert_eq!(glob_to_regex("**/foo"), r"^(?:.*/)?foo$");
+    }
+
+    #[test]
+    fn trailing_double_star_matches_everything_remaining() {
+        assert_eq!(glob_to_regex("foo/**"), r"^foo/.*$");
+    }
+
+    #[test]
+    fn single_star_does_not_cross_a_path_separator() {
+        assert_eq!(glob_to_regex("*.rs"), r"^[^/]*\.rs$");
+    }
+
+    #[test]
+    fn question_mark_matches_a_single_non_separator_char() {
+        assert_eq!(glob_to_regex("a?c"), r"^a[^/]c$");
+    }
 }